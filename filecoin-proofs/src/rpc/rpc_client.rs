@@ -6,6 +6,9 @@ use jsonrpc_core::futures::Future;
 use jsonrpc_http_server::*;
 use jsonrpc_client_transports::transports::http;
 
+use crate::rpc::rpc_server::WireProof;
+use crate::types::{Commitment, SectorSize};
+
 
 #[derive(Clone)]
 pub struct RpcClient(TypedClient);
@@ -24,10 +27,43 @@ impl RpcClient {
     pub fn fail(&self) -> impl Future<Item = (), Error = RpcError> {
         self.0.call_method("fail", "()", ())
     }
-    
+
     pub fn notify(&self, value: u64) -> impl Future<Item = (), Error = RpcError> {
         self.0.notify("notify", (value,))
     }
+
+    /// Submits sector data at `sector_path` to the worker and gets back the
+    /// root commitment of the tree it built over that data.
+    pub fn submit_sector(
+        &self,
+        sector_path: String,
+        sector_size: SectorSize,
+    ) -> impl Future<Item = Commitment, Error = RpcError> {
+        self.0
+            .call_method("submit_sector", "Commitment", (sector_path, u64::from(sector_size)))
+    }
+
+    /// Requests an inclusion proof for `node_index` of the sector that
+    /// previously submitted to `commitment`.
+    pub fn gen_inclusion_proof(
+        &self,
+        commitment: Commitment,
+        node_index: usize,
+    ) -> impl Future<Item = WireProof, Error = RpcError> {
+        self.0
+            .call_method("gen_inclusion_proof", "WireProof", (commitment, node_index))
+    }
+
+    /// Asks the worker to check a proof it (or another worker building the
+    /// same tree) produced against `commitment`.
+    pub fn verify_proof(
+        &self,
+        commitment: Commitment,
+        proof: WireProof,
+    ) -> impl Future<Item = bool, Error = RpcError> {
+        self.0
+            .call_method("verify_proof", "bool", (commitment, proof))
+    }
 }
 
 fn id<T>(t: T) -> T {
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use generic_array::typenum::U2;
+use jsonrpc_core::{Error as RpcError, IoHandler, Params, Value};
+use jsonrpc_http_server::*;
+use paired::bls12_381::{Bls12, Fr};
+use serde::{Deserialize, Serialize};
+use storage_proofs::drgraph::{new_seed, ApiVersion, BucketGraph, Graph};
+use storage_proofs::hasher::poseidon::{PoseidonDomain, PoseidonFunction, PoseidonHasher};
+use storage_proofs::hasher::Hasher;
+use storage_proofs::merkle::{MerkleTree, Proof};
+
+use crate::api::util::{as_safe_commitment, commitment_from_fr};
+use crate::types::{Commitment, SectorSize};
+
+/// The arity every tree this service builds uses; matches the binary trees
+/// exercised by `storage_proofs::drgraph`'s own Poseidon tests.
+type Arity = U2;
+type SectorTree = MerkleTree<PoseidonDomain, PoseidonFunction, Arity>;
+
+/// Node size assumed throughout `storage_proofs::drgraph::Graph` --
+/// `merkle_tree` rejects any other value.
+const NODE_SIZE: usize = 32;
+
+/// Wire encoding of a `merkle::Proof`: every hash in its lemma (the leaf,
+/// its siblings, and the root) encoded as a `Commitment`, plus the branch
+/// taken at each level. Kept separate from `Proof` itself so the RPC wire
+/// format doesn't change shape if the underlying tree crate does.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WireProof {
+    lemma: Vec<Commitment>,
+    path: Vec<usize>,
+}
+
+impl WireProof {
+    fn from_proof(proof: &Proof<PoseidonDomain, Arity>) -> Self {
+        WireProof {
+            lemma: proof
+                .lemma()
+                .iter()
+                .map(|hash| commitment_from_fr::<Bls12>(Fr::from(*hash)))
+                .collect(),
+            path: proof.path().to_vec(),
+        }
+    }
+
+    fn to_proof(&self) -> Result<Proof<PoseidonDomain, Arity>> {
+        let lemma = self
+            .lemma
+            .iter()
+            .map(|commitment| as_safe_commitment::<PoseidonDomain, _>(commitment, "lemma entry"))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Proof::new(lemma, self.path.clone()))
+    }
+}
+
+/// Holds every sector this service has built a tree for, keyed by the
+/// sector's root commitment, so `gen_inclusion_proof` and `verify_proof`
+/// can find it again without the caller re-submitting the data.
+#[derive(Default)]
+struct ProofWorker {
+    // The node count rides alongside the tree so `gen_inclusion_proof` can
+    // validate a caller-supplied index before it ever reaches `gen_proof`.
+    sectors: Mutex<HashMap<Commitment, (SectorTree, usize)>>,
+}
+
+impl ProofWorker {
+    fn submit_sector(&self, sector_path: &str, sector_size: SectorSize) -> Result<Commitment> {
+        let data = std::fs::read(sector_path)
+            .with_context(|| format!("failed to read sector at {}", sector_path))?;
+
+        if data.len() != u64::from(sector_size) as usize {
+            bail!("sector data does not match the declared sector size");
+        }
+
+        let nodes = data.len() / NODE_SIZE;
+        let graph = BucketGraph::<PoseidonHasher>::new(
+            nodes,
+            2,
+            0,
+            [0u8; 32],
+            ApiVersion::V1_1,
+            new_seed(),
+        );
+        let tree = graph.merkle_tree::<Arity>(&data, NODE_SIZE)?;
+        let commitment = commitment_from_fr::<Bls12>(Fr::from(tree.root()));
+
+        self.sectors
+            .lock()
+            .expect("sectors lock poisoned")
+            .insert(commitment, (tree, nodes));
+
+        Ok(commitment)
+    }
+
+    fn gen_inclusion_proof(&self, commitment: Commitment, node_index: usize) -> Result<WireProof> {
+        let sectors = self.sectors.lock().expect("sectors lock poisoned");
+        let (tree, nodes) = sectors
+            .get(&commitment)
+            .with_context(|| "unknown commitment: submit the sector before proving it")?;
+
+        if node_index >= *nodes {
+            bail!(
+                "node index {} out of range for a sector with {} nodes",
+                node_index,
+                nodes
+            );
+        }
+
+        Ok(WireProof::from_proof(&tree.gen_proof(node_index)))
+    }
+
+    fn verify_proof(&self, commitment: Commitment, proof: &WireProof) -> Result<bool> {
+        let root: PoseidonDomain = as_safe_commitment(&commitment, "commitment")?;
+        let proof = proof.to_proof()?;
+
+        Ok(proof.root() == root && proof.validate::<<PoseidonHasher as Hasher>::Function>())
+    }
+}
+
+fn invalid_params(err: impl std::fmt::Display) -> RpcError {
+    RpcError::invalid_params(err.to_string())
+}
+
+/// A running `submit_sector`/`gen_inclusion_proof`/`verify_proof` service,
+/// keeping the underlying HTTP server (and the worker state it closes
+/// over) alive for as long as this value is.
+pub struct RunningServer {
+    pub uri: String,
+    _server: Server,
+    _worker: Arc<ProofWorker>,
+}
+
+/// Builds and starts the proof-generation RPC service: submit a sector's
+/// data to get back its root `Commitment`, request an inclusion proof for
+/// a node index, or verify a previously generated proof -- all running as
+/// a networked worker rather than only in-process.
+pub struct RpcServer;
+
+impl RpcServer {
+    /// Starts the service on an OS-assigned local port. `configure` is
+    /// applied to the fully registered `IoHandler` before the server
+    /// starts, so callers (tests, in particular) can layer in additional
+    /// behavior without this module needing to know about it.
+    pub fn serve<F>(configure: F) -> RunningServer
+    where
+        F: FnOnce(IoHandler) -> IoHandler,
+    {
+        let worker = Arc::new(ProofWorker::default());
+        let mut io = IoHandler::new();
+
+        {
+            let worker = Arc::clone(&worker);
+            io.add_method("submit_sector", move |params: Params| {
+                let (sector_path, sector_size): (String, u64) = params.parse()?;
+                worker
+                    .submit_sector(&sector_path, SectorSize(sector_size))
+                    .map(|commitment| {
+                        Value::from(serde_json::to_value(&commitment).expect("commitment serializes"))
+                    })
+                    .map_err(invalid_params)
+            });
+        }
+
+        {
+            let worker = Arc::clone(&worker);
+            io.add_method("gen_inclusion_proof", move |params: Params| {
+                let (commitment, node_index): (Commitment, usize) = params.parse()?;
+                worker
+                    .gen_inclusion_proof(commitment, node_index)
+                    .map(|proof| Value::from(serde_json::to_value(&proof).expect("proof serializes")))
+                    .map_err(invalid_params)
+            });
+        }
+
+        {
+            let worker = Arc::clone(&worker);
+            io.add_method("verify_proof", move |params: Params| {
+                let (commitment, proof): (Commitment, WireProof) = params.parse()?;
+                worker
+                    .verify_proof(commitment, &proof)
+                    .map(Value::from)
+                    .map_err(invalid_params)
+            });
+        }
+
+        let io = configure(io);
+
+        let server = ServerBuilder::new(io)
+            .start_http(&"127.0.0.1:0".parse().expect("valid loopback address"))
+            .expect("failed to start proof RPC server");
+        let uri = format!("http://{}", server.address());
+
+        RunningServer {
+            uri,
+            _server: server,
+            _worker: worker,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hyper::rt;
+    use jsonrpc_client_transports::transports::http;
+    use jsonrpc_core::futures::Future;
+
+    use super::*;
+    use crate::rpc::rpc_client::RpcClient;
+
+    #[test]
+    fn submits_proves_and_verifies_a_sector_over_rpc() {
+        let leaves = 8;
+        let sector_bytes = vec![7u8; NODE_SIZE * leaves];
+        let sector_path = std::env::temp_dir().join(format!("rpc-server-test-{}.dat", std::process::id()));
+        std::fs::write(&sector_path, &sector_bytes).expect("writes temp sector");
+        let sector_path = sector_path.to_str().expect("valid path").to_string();
+        let sector_size = SectorSize(sector_bytes.len() as u64);
+
+        let server = RpcServer::serve(|io| io);
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        let run = http::connect(&server.uri)
+            .and_then(move |client: RpcClient| {
+                let for_inclusion = client.clone();
+                let for_verify = client.clone();
+
+                client
+                    .submit_sector(sector_path, sector_size)
+                    .and_then(move |commitment| {
+                        for_inclusion
+                            .gen_inclusion_proof(commitment, 0)
+                            .map(move |proof| (commitment, proof))
+                    })
+                    .and_then(move |(commitment, proof)| for_verify.verify_proof(commitment, proof))
+                    .and_then(move |verified| {
+                        let _ = tx.send(verified);
+                        Ok(())
+                    })
+            })
+            .map_err(|e| panic!("rpc error: {:?}", e));
+
+        rt::run(run);
+
+        let verified = rx.recv_timeout(Duration::from_secs(5)).expect("rpc round trip");
+        assert!(verified);
+    }
+
+    #[test]
+    fn gen_inclusion_proof_rejects_out_of_range_node_index() {
+        let leaves = 8;
+        let sector_bytes = vec![9u8; NODE_SIZE * leaves];
+        let sector_path = std::env::temp_dir().join(format!("rpc-server-test-oob-{}.dat", std::process::id()));
+        std::fs::write(&sector_path, &sector_bytes).expect("writes temp sector");
+
+        let worker = ProofWorker::default();
+        let commitment = worker
+            .submit_sector(sector_path.to_str().expect("valid path"), SectorSize(sector_bytes.len() as u64))
+            .expect("submits sector");
+
+        assert!(worker.gen_inclusion_proof(commitment, leaves).is_err());
+    }
+}
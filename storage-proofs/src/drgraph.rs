@@ -1,14 +1,22 @@
 use std::cmp;
+use std::collections::HashMap;
 use std::marker::PhantomData;
 
 use rand::{ChaChaRng, OsRng, Rng, SeedableRng};
 use rayon::prelude::*;
+use sha2::{Digest, Sha256};
 
 use error::*;
+use feistel::{self, FeistelPrecomputed};
 use hasher::pedersen::PedersenHasher;
+use hasher::poseidon::PoseidonArity;
 use hasher::{Domain, HashFunction, Hasher};
-use merkle::MerkleTree;
+use merkle::{MerkleTree, Proof};
 use parameter_cache::ParameterSetIdentifier;
+use store::{
+    build_disk_backed_tree, open_level_cache_tree, DiskMerkleTree, LevelCacheMerkleTree,
+    ReplicaConfig, StoreConfig,
+};
 use util::data_at_node;
 
 /// The default hasher currently in use.
@@ -16,6 +24,36 @@ pub type DefaultTreeHasher = PedersenHasher;
 
 pub const PARALLELL_MERKLE: bool = false;
 
+/// The domain-separation tag used to derive the per-graph seed for the
+/// `V1_1` parent sampling scheme.
+pub const DRSAMPLE_V1_1_DST: &str = "Filecoin_DRSample_V1_1";
+
+/// Selects the algorithm used to sample a node's parents.
+///
+/// `V1_0` keeps the original `ChaChaRng`-seeded bucket sampling, which ties
+/// the graph layout to the behavior of a specific RNG implementation.
+/// `V1_1` instead derives parents directly from SHA256, so the layout is
+/// portable across `rand` versions and implementations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ApiVersion {
+    V1_0,
+    V1_1,
+}
+
+/// Derives a per-graph domain seed by hashing a domain-separation tag
+/// together with the porep id. Used by the `V1_1` parent sampling scheme so
+/// that parent derivation is bound to both the algorithm and the specific
+/// porep instance.
+pub fn derive_porep_domain_seed(domain_separation_tag: &str, porep_id: [u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.input(domain_separation_tag.as_bytes());
+    hasher.input(&porep_id);
+
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(hasher.result().as_slice());
+    seed
+}
+
 /// A depth robust graph.
 pub trait Graph<H: Hasher>: ::std::fmt::Debug + Clone + PartialEq + Eq {
     /// Returns the expected size of all nodes in the graph.
@@ -23,12 +61,15 @@ pub trait Graph<H: Hasher>: ::std::fmt::Debug + Clone + PartialEq + Eq {
         self.size() * node_size
     }
 
-    /// Builds a merkle tree based on the given data.
-    fn merkle_tree<'a>(
+    /// Builds a merkle tree of arity `A` based on the given data. Using a
+    /// higher arity (4, 8, ...) trades more children hashed per internal
+    /// node for a shallower tree, cutting the number of hash invocations
+    /// needed to reach the root on large sectors.
+    fn merkle_tree<'a, A: PoseidonArity>(
         &self,
         data: &'a [u8],
         node_size: usize,
-    ) -> Result<MerkleTree<H::Domain, H::Function>> {
+    ) -> Result<MerkleTree<H::Domain, H::Function, A>> {
         if data.len() != (node_size * self.size()) as usize {
             return Err(Error::InvalidMerkleTreeArgs(
                 data.len(),
@@ -59,9 +100,84 @@ pub trait Graph<H: Hasher>: ::std::fmt::Debug + Clone + PartialEq + Eq {
         }
     }
 
-    /// Returns the merkle tree depth.
-    fn merkle_tree_depth(&self) -> u64 {
-        graph_height(self.size()) as u64
+    /// Returns the merkle tree depth (row count) for an arity-`A` tree over
+    /// this graph's leaves.
+    fn merkle_tree_depth<A: PoseidonArity>(&self) -> u64 {
+        get_merkle_tree_row_count(self.size(), A::to_usize()) as u64
+    }
+
+    /// Builds a disk-backed merkle tree of arity `A`: every row from
+    /// `config.rows_to_discard` up to the root is written to `config`'s
+    /// store, and the returned tree keeps only its root and the store
+    /// handle. The bottom `config.rows_to_discard` rows are not persisted;
+    /// they are recovered later, while proving, via `lc_merkle_tree`. All
+    /// rows of the tree are computed in memory while it is built.
+    fn merkle_tree_with_config<'a, A: PoseidonArity>(
+        &self,
+        data: &'a [u8],
+        node_size: usize,
+        config: StoreConfig,
+    ) -> Result<DiskMerkleTree<H>> {
+        if data.len() != (node_size * self.size()) as usize {
+            return Err(Error::InvalidMerkleTreeArgs(
+                data.len(),
+                node_size,
+                self.size(),
+            ));
+        }
+
+        if node_size != 32 {
+            return Err(Error::InvalidNodeSize(node_size));
+        }
+
+        let leaves: Vec<H::Domain> = (0..self.size())
+            .map(|i| {
+                let d = data_at_node(&data, i, node_size).expect("data_at_node math failed");
+                H::Domain::try_from_bytes(d.clone()).unwrap() // FIXME: don't unwrap.
+            })
+            .collect();
+
+        Ok(build_disk_backed_tree::<H>(
+            &leaves,
+            &config,
+            config.rows_to_discard,
+            A::to_usize(),
+        )?)
+    }
+
+    /// Builds a level-cache merkle tree of arity `A`: only the top
+    /// `merkle_tree_depth::<A>() - config.rows_to_discard` rows are kept
+    /// resident, and `replica_config` points at the on-disk replica used to
+    /// regenerate the discarded rows on demand while generating proofs.
+    fn lc_merkle_tree<A: PoseidonArity>(
+        &self,
+        node_size: usize,
+        config: StoreConfig,
+        replica_config: ReplicaConfig,
+    ) -> Result<LevelCacheMerkleTree<H>> {
+        if node_size != 32 {
+            return Err(Error::InvalidNodeSize(node_size));
+        }
+
+        Ok(open_level_cache_tree::<H>(
+            &config,
+            replica_config,
+            self.size(),
+            A::to_usize(),
+        )?)
+    }
+
+    /// Builds one shared proof covering every leaf in `challenges` against
+    /// `tree`. `tree.gen_proof` is still called once per challenge, but
+    /// identical lemma entries (most commonly the root, which every leaf's
+    /// path passes through, and siblings shared by nearby leaves) are
+    /// interned once into `BatchMerkleProof::hashes` instead of once per leaf.
+    fn gen_batch_proof<A: PoseidonArity>(
+        &self,
+        tree: &MerkleTree<H::Domain, H::Function, A>,
+        challenges: &[usize],
+    ) -> BatchMerkleProof<H, A> {
+        gen_batch_proof::<H, A>(tree, challenges)
     }
 
     /// Returns a sorted list of all parents of this node.
@@ -73,17 +189,139 @@ pub trait Graph<H: Hasher>: ::std::fmt::Debug + Clone + PartialEq + Eq {
     /// Returns the degree of the graph.
     fn degree(&self) -> usize;
 
-    fn new(nodes: usize, base_degree: usize, expansion_degree: usize, seed: [u32; 7]) -> Self;
+    fn new(
+        nodes: usize,
+        base_degree: usize,
+        expansion_degree: usize,
+        porep_id: [u8; 32],
+        api_version: ApiVersion,
+        seed: [u32; 7],
+    ) -> Self;
     fn seed(&self) -> [u32; 7];
 
     // Returns true if a node's parents have lower index than the node.
     fn forward(&self) -> bool {
         true
     }
+
+    /// Returns the number of expander (cross-layer) parents per node, on
+    /// top of the base-degree DRG parents. `0` for graphs that are DRG-only.
+    fn expansion_degree(&self) -> usize {
+        0
+    }
 }
 
-pub fn graph_height(size: usize) -> usize {
-    (size as f64).log2().ceil() as usize
+/// One leaf's authentication path within a `BatchMerkleProof`, expressed as
+/// indices into `BatchMerkleProof::hashes` rather than owned hashes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct BatchLeafProof {
+    leaf_index: usize,
+    // Indices into `BatchMerkleProof::hashes`, in the order the underlying
+    // `Proof::lemma` returned them.
+    lemma: Vec<usize>,
+    path: Vec<usize>,
+}
+
+/// An inclusion proof for many leaves of the same tree, built by
+/// `gen_batch_proof` and checked in one pass by `verify_batch`.
+///
+/// Independently calling `MerkleTree::gen_proof` once per challenged leaf
+/// stores the shared root, and any sibling hash two nearby leaves' paths
+/// have in common, once per leaf. `BatchMerkleProof` instead interns every
+/// distinct hash across the whole batch into `hashes` and has each leaf's
+/// path reference into it, so the amortized cost per leaf drops as the
+/// batch grows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchMerkleProof<H: Hasher, A: PoseidonArity> {
+    pub root: H::Domain,
+    hashes: Vec<H::Domain>,
+    leaves: Vec<BatchLeafProof>,
+    _a: PhantomData<A>,
+}
+
+/// Builds a `BatchMerkleProof` for `challenges` against `tree`. See
+/// `Graph::gen_batch_proof`, which most callers should use instead of
+/// calling this directly.
+pub fn gen_batch_proof<H: Hasher, A: PoseidonArity>(
+    tree: &MerkleTree<H::Domain, H::Function, A>,
+    challenges: &[usize],
+) -> BatchMerkleProof<H, A> {
+    let mut hashes: Vec<H::Domain> = Vec::new();
+    let mut index_of: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut leaves = Vec::with_capacity(challenges.len());
+    let mut root: Option<H::Domain> = None;
+
+    for &challenge in challenges {
+        let proof = tree.gen_proof(challenge);
+
+        let this_root = proof.root();
+        match &root {
+            None => root = Some(this_root),
+            Some(r) => assert_eq!(
+                *r, this_root,
+                "challenges must all be proved against the same tree"
+            ),
+        }
+
+        let lemma = proof
+            .lemma()
+            .iter()
+            .map(|hash| {
+                let key = hash.into_bytes();
+                *index_of.entry(key).or_insert_with(|| {
+                    hashes.push(hash.clone());
+                    hashes.len() - 1
+                })
+            })
+            .collect();
+
+        leaves.push(BatchLeafProof {
+            leaf_index: challenge,
+            lemma,
+            path: proof.path().to_vec(),
+        });
+    }
+
+    BatchMerkleProof {
+        root: root.expect("challenges must not be empty"),
+        hashes,
+        leaves,
+        _a: PhantomData,
+    }
+}
+
+/// Checks every leaf proved by `proof` against its single shared root in
+/// one pass, reconstructing each leaf's lemma from the deduplicated
+/// `hashes` pool rather than re-deriving shared sibling hashes once per
+/// leaf.
+pub fn verify_batch<H: Hasher, A: PoseidonArity>(proof: &BatchMerkleProof<H, A>) -> bool {
+    proof.leaves.iter().all(|leaf| {
+        let lemma: Vec<H::Domain> = leaf
+            .lemma
+            .iter()
+            .map(|&i| proof.hashes[i].clone())
+            .collect();
+        let leaf_proof = Proof::<H::Domain, A>::new(lemma, leaf.path.clone());
+
+        leaf_proof.root() == proof.root && leaf_proof.validate::<H::Function>()
+    })
+}
+
+/// Returns the number of rows (including the leaf row and the root) of an
+/// arity-`arity` Merkle tree over `leaves` leaves.
+pub fn get_merkle_tree_row_count(leaves: usize, arity: usize) -> usize {
+    if leaves <= 1 {
+        return leaves;
+    }
+
+    let mut row_count = 1;
+    let mut remaining = leaves;
+    while remaining > 1 {
+        remaining = (remaining + arity - 1) / arity;
+        row_count += 1;
+    }
+
+    row_count
 }
 
 /// Bucket sampling algorithm.
@@ -92,15 +330,24 @@ pub struct BucketGraph<H: Hasher> {
     nodes: usize,
     base_degree: usize,
     seed: [u32; 7],
+    api_version: ApiVersion,
+    // Only used by `ApiVersion::V1_1`; derived once in `new` from
+    // `porep_id` so `parents` never has to recompute it.
+    domain_seed: [u8; 32],
     _h: PhantomData<H>,
 }
 
 impl<H: Hasher> ParameterSetIdentifier for BucketGraph<H> {
     fn parameter_set_identifier(&self) -> String {
-        // NOTE: Seed is not included because it does not influence parameter generation.
+        // NOTE: seed is not included because it does not influence parameter
+        // generation. `api_version` is included because it selects a
+        // different parent-sampling algorithm (V1_0 vs V1_1 produce
+        // differently shaped graphs for the same size/degree), and
+        // `domain_seed` because it is derived from `porep_id`, which keys
+        // the V1_1 sampling scheme.
         format!(
-            "drgraph::BucketGraph{{size: {}; degree: {}}}",
-            self.nodes, self.base_degree,
+            "drgraph::BucketGraph{{size: {}; degree: {}; api_version: {:?}; domain_seed: {:?}}}",
+            self.nodes, self.base_degree, self.api_version, self.domain_seed,
         )
     }
 }
@@ -116,31 +363,40 @@ impl<H: Hasher> Graph<H> for BucketGraph<H> {
             // Special case for the second node, it references only the first one.
             1 => vec![0; m as usize],
             _ => {
-                // seed = self.seed | node
-                let mut seed = [0u32; 8];
-                seed[0..7].copy_from_slice(&self.seed);
-                seed[7] = node as u32;
-                let mut rng = ChaChaRng::from_seed(&seed);
-
-                let mut parents = Vec::with_capacity(m);
-                for k in 0..m {
-                    // iterate over m meta nodes of the ith real node
-                    // simulate the edges that we would add from previous graph nodes
-                    // if any edge is added from a meta node of jth real node then add edge (j,i)
-                    let logi = ((node * m) as f32).log2().floor() as usize;
-                    let j = rng.gen::<usize>() % logi;
-                    let jj = cmp::min(node * m + k, 1 << (j + 1));
-                    let back_dist = rng.gen_range(cmp::max(jj >> 1, 2), jj + 1);
-                    let out = (node * m + k - back_dist) / m;
-
-                    // remove self references and replace with reference to previous node
-                    if out == node {
-                        parents.push(node - 1);
-                    } else {
-                        assert!(out <= node);
-                        parents.push(out);
+                let mut parents = match self.api_version {
+                    ApiVersion::V1_0 => {
+                        // seed = self.seed | node
+                        let mut seed = [0u32; 8];
+                        seed[0..7].copy_from_slice(&self.seed);
+                        seed[7] = node as u32;
+                        let mut rng = ChaChaRng::from_seed(&seed);
+
+                        let mut parents = Vec::with_capacity(m);
+                        for k in 0..m {
+                            // iterate over m meta nodes of the ith real node
+                            // simulate the edges that we would add from previous graph nodes
+                            // if any edge is added from a meta node of jth real node then add edge (j,i)
+                            let logi = ((node * m) as f32).log2().floor() as usize;
+                            let j = rng.gen::<usize>() % logi;
+                            let jj = cmp::min(node * m + k, 1 << (j + 1));
+                            let back_dist = rng.gen_range(cmp::max(jj >> 1, 2), jj + 1);
+                            let out = (node * m + k - back_dist) / m;
+
+                            // remove self references and replace with reference to previous node
+                            if out == node {
+                                parents.push(node - 1);
+                            } else {
+                                assert!(out <= node);
+                                parents.push(out);
+                            }
+                        }
+
+                        parents
                     }
-                }
+                    ApiVersion::V1_1 => (0..m)
+                        .map(|k| self.derive_parent_v1_1(node, k))
+                        .collect(),
+                };
 
                 parents.sort_unstable();
 
@@ -163,21 +419,154 @@ impl<H: Hasher> Graph<H> for BucketGraph<H> {
         self.seed
     }
 
-    fn new(nodes: usize, base_degree: usize, expansion_degree: usize, seed: [u32; 7]) -> Self {
+    fn new(
+        nodes: usize,
+        base_degree: usize,
+        expansion_degree: usize,
+        porep_id: [u8; 32],
+        api_version: ApiVersion,
+        seed: [u32; 7],
+    ) -> Self {
         assert_eq!(expansion_degree, 0);
         BucketGraph {
             nodes,
             base_degree,
             seed,
+            api_version,
+            domain_seed: derive_porep_domain_seed(DRSAMPLE_V1_1_DST, porep_id),
             _h: PhantomData,
         }
     }
 }
 
+impl<H: Hasher> BucketGraph<H> {
+    /// Derives parent `k` of node `v` (`v >= 2`) for `ApiVersion::V1_1`.
+    ///
+    /// Hashes `domain_seed || v_le_bytes || k_le_bytes` with SHA256, reduces
+    /// the first 8 bytes of the digest mod `v` to pick the parent, then
+    /// forces the last parent (`k == degree - 1`) to be `v - 1` so every
+    /// node has its immediate predecessor as a parent.
+    fn derive_parent_v1_1(&self, node: usize, k: usize) -> usize {
+        if k == self.base_degree - 1 {
+            return node - 1;
+        }
+
+        let mut data = Vec::with_capacity(32 + 8 + 8);
+        data.extend_from_slice(&self.domain_seed);
+        data.extend_from_slice(&(node as u64).to_le_bytes());
+        data.extend_from_slice(&(k as u64).to_le_bytes());
+
+        let digest = Sha256::digest(&data);
+        let mut first_8 = [0u8; 8];
+        first_8.copy_from_slice(&digest[..8]);
+
+        (u64::from_le_bytes(first_8) % node as u64) as usize
+    }
+}
+
 pub fn new_seed() -> [u32; 7] {
     OsRng::new().unwrap().gen()
 }
 
+/// Wraps a `BucketGraph` with expander parents, so that stacked/layered DRG
+/// constructions (one graph per layer, connected by expansion edges) can be
+/// built. Each node gets `expansion_degree` extra parents on top of its
+/// `BucketGraph` parents, computed via a keyed Feistel permutation over
+/// `nodes * expansion_degree`. The permutation of (node, slot) pairs is a
+/// bijection, so every node is picked as an expander parent exactly
+/// `expansion_degree` times.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StackedBucketGraph<H: Hasher> {
+    base_graph: BucketGraph<H>,
+    expansion_degree: usize,
+    feistel_key: u64,
+    feistel_precomputed: FeistelPrecomputed,
+}
+
+impl<H: Hasher> ParameterSetIdentifier for StackedBucketGraph<H> {
+    fn parameter_set_identifier(&self) -> String {
+        format!(
+            "stacked_drgraph::StackedBucketGraph{{expansion_degree: {}; base_graph: {}}}",
+            self.expansion_degree,
+            self.base_graph.parameter_set_identifier(),
+        )
+    }
+}
+
+impl<H: Hasher> StackedBucketGraph<H> {
+    /// Returns the `expansion_degree` expander parents of `node`, derived by
+    /// permuting `node * expansion_degree + k` (for each slot `k`) through a
+    /// Feistel cipher keyed from the graph seed and folding the result back
+    /// into `[0, size())`.
+    pub fn expanded_parents(&self, node: usize) -> Vec<usize> {
+        let total = (self.size() * self.expansion_degree) as u64;
+
+        (0..self.expansion_degree)
+            .map(|k| {
+                let i = (node * self.expansion_degree + k) as u64;
+                let permuted = feistel::permute(total, i, self.feistel_key, self.feistel_precomputed);
+                permuted as usize / self.expansion_degree
+            })
+            .collect()
+    }
+}
+
+impl<H: Hasher> Graph<H> for StackedBucketGraph<H> {
+    fn expected_size(&self, node_size: usize) -> usize {
+        self.base_graph.expected_size(node_size)
+    }
+
+    #[inline]
+    fn parents(&self, node: usize) -> Vec<usize> {
+        let mut parents = self.base_graph.parents(node);
+        parents.extend(self.expanded_parents(node));
+        parents.sort_unstable();
+
+        parents
+    }
+
+    #[inline]
+    fn size(&self) -> usize {
+        self.base_graph.size()
+    }
+
+    #[inline]
+    fn degree(&self) -> usize {
+        self.base_graph.degree() + self.expansion_degree
+    }
+
+    fn seed(&self) -> [u32; 7] {
+        self.base_graph.seed()
+    }
+
+    fn new(
+        nodes: usize,
+        base_degree: usize,
+        expansion_degree: usize,
+        porep_id: [u8; 32],
+        api_version: ApiVersion,
+        seed: [u32; 7],
+    ) -> Self {
+        let base_graph = BucketGraph::new(nodes, base_degree, 0, porep_id, api_version, seed);
+
+        // Derive the Feistel key from the graph seed so that expander
+        // parents are reproducible from the seed alone.
+        let feistel_key = u64::from(seed[0]) | (u64::from(seed[1]) << 32);
+        let feistel_precomputed = feistel::precompute((nodes * expansion_degree) as u64);
+
+        StackedBucketGraph {
+            base_graph,
+            expansion_degree,
+            feistel_key,
+            feistel_precomputed,
+        }
+    }
+
+    fn expansion_degree(&self) -> usize {
+        self.expansion_degree
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -185,7 +574,10 @@ mod tests {
     use memmap::MmapMut;
     use memmap::MmapOptions;
 
+    use generic_array::typenum::{U2, U4, U8};
+
     use drgraph::new_seed;
+    use hasher::poseidon::PoseidonHasher;
     use hasher::{Blake2sHasher, PedersenHasher, Sha256Hasher};
 
     // Create and return an object of MmapMut backed by in-memory copy of data.
@@ -198,7 +590,14 @@ mod tests {
     fn graph_bucket<H: Hasher>() {
         for size in vec![3, 10, 200, 2000] {
             for degree in 2..12 {
-                let g = BucketGraph::<H>::new(size, degree, 0, new_seed());
+                let g = BucketGraph::<H>::new(
+                    size,
+                    degree,
+                    0,
+                    [0u8; 32],
+                    ApiVersion::V1_0,
+                    new_seed(),
+                );
 
                 assert_eq!(g.size(), size, "wrong nodes count");
 
@@ -244,12 +643,12 @@ mod tests {
     }
 
     fn gen_proof<H: Hasher>() {
-        let g = BucketGraph::<H>::new(5, 3, 0, new_seed());
+        let g = BucketGraph::<H>::new(5, 3, 0, [0u8; 32], ApiVersion::V1_0, new_seed());
         let node_size = 32;
         let data = vec![2u8; node_size * 5];
 
         let mmapped = &mmap_from(&data);
-        let tree = g.merkle_tree(mmapped, node_size).unwrap();
+        let tree = g.merkle_tree::<U2>(mmapped, node_size).unwrap();
         let proof = tree.gen_proof(2);
 
         assert!(proof.validate::<H::Function>());
@@ -269,4 +668,188 @@ mod tests {
     fn gen_proof_blake2s() {
         gen_proof::<Blake2sHasher>()
     }
+
+    fn gen_batch_proof<H: Hasher>() {
+        let g = BucketGraph::<H>::new(8, 3, 0, [0u8; 32], ApiVersion::V1_0, new_seed());
+        let node_size = 32;
+        let data = vec![2u8; node_size * 8];
+
+        let mmapped = &mmap_from(&data);
+        let tree = g.merkle_tree::<U2>(mmapped, node_size).unwrap();
+
+        // 1 and 2 lie in the same half of the tree, so their paths share the
+        // top-level sibling and the root (and every path shares the root);
+        // interning should keep `hashes` well below one entry per leaf per
+        // level.
+        let challenges = vec![1, 2, 6];
+        let batch = g.gen_batch_proof(&tree, &challenges);
+
+        assert!(verify_batch(&batch));
+        assert!(batch.hashes.len() < challenges.len() * tree.gen_proof(1).lemma().len());
+    }
+
+    #[test]
+    fn gen_batch_proof_pedersen() {
+        gen_batch_proof::<PedersenHasher>()
+    }
+
+    #[test]
+    fn gen_batch_proof_sha256() {
+        gen_batch_proof::<Sha256Hasher>()
+    }
+
+    #[test]
+    fn gen_batch_proof_blake2s() {
+        gen_batch_proof::<Blake2sHasher>()
+    }
+
+    #[test]
+    fn parents_v1_1_last_parent_is_predecessor() {
+        let degree = 5;
+        let g = BucketGraph::<PedersenHasher>::new(
+            200,
+            degree,
+            0,
+            [1u8; 32],
+            ApiVersion::V1_1,
+            new_seed(),
+        );
+
+        for node in 2..200 {
+            let parents = g.parents(node);
+            assert_eq!(parents.len(), degree);
+            assert!(parents.contains(&(node - 1)));
+            assert!(parents.iter().all(|&p| p < node));
+        }
+    }
+
+    #[test]
+    fn parents_v1_1_deterministic_per_porep_id() {
+        let g1 = BucketGraph::<PedersenHasher>::new(
+            200,
+            5,
+            0,
+            [7u8; 32],
+            ApiVersion::V1_1,
+            [0u32; 7],
+        );
+        let g2 = BucketGraph::<PedersenHasher>::new(
+            200,
+            5,
+            0,
+            [7u8; 32],
+            ApiVersion::V1_1,
+            [0u32; 7],
+        );
+        let g3 = BucketGraph::<PedersenHasher>::new(
+            200,
+            5,
+            0,
+            [9u8; 32],
+            ApiVersion::V1_1,
+            [0u32; 7],
+        );
+
+        assert_eq!(g1.parents(150), g2.parents(150));
+        assert_ne!(g1.parents(150), g3.parents(150));
+    }
+
+    #[test]
+    fn stacked_bucket_graph_degree_and_parents() {
+        let nodes = 200;
+        let base_degree = 5;
+        let expansion_degree = 8;
+
+        let g = StackedBucketGraph::<PedersenHasher>::new(
+            nodes,
+            base_degree,
+            expansion_degree,
+            [3u8; 32],
+            ApiVersion::V1_0,
+            new_seed(),
+        );
+
+        assert_eq!(g.degree(), base_degree + expansion_degree);
+        assert_eq!(g.expansion_degree(), expansion_degree);
+
+        for node in 0..nodes {
+            let parents = g.parents(node);
+            assert_eq!(parents.len(), base_degree + expansion_degree);
+            assert!(parents.iter().all(|&p| p < nodes));
+        }
+    }
+
+    #[test]
+    fn merkle_tree_with_config_round_trips_through_lc_merkle_tree() {
+        let nodes = 8;
+        let node_size = 32;
+        let g = BucketGraph::<PoseidonHasher>::new(
+            nodes,
+            3,
+            0,
+            [0u8; 32],
+            ApiVersion::V1_1,
+            new_seed(),
+        );
+
+        let data = vec![5u8; node_size * nodes];
+
+        let dir = std::env::temp_dir().join(format!(
+            "drgraph-merkle-tree-with-config-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let replica_path = dir.join("replica.dat");
+        std::fs::write(&replica_path, &data).unwrap();
+        let replica_config = ReplicaConfig::new(&replica_path, 0);
+
+        let config = StoreConfig::new(&dir, "tree-test", 1);
+
+        let tree = g
+            .merkle_tree_with_config::<U2>(&data, node_size, config.clone())
+            .unwrap();
+        let root = tree.root();
+
+        let mut lc_tree = g
+            .lc_merkle_tree::<U2>(node_size, config, replica_config)
+            .unwrap();
+
+        for leaf_index in 0..nodes {
+            let proof = lc_tree.gen_proof(leaf_index).unwrap();
+            assert_eq!(proof.root(), root);
+            assert!(proof.validate());
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn merkle_tree_row_count_matches_arity() {
+        // A binary tree over 8 leaves: 8 -> 4 -> 2 -> 1, so 4 rows.
+        assert_eq!(get_merkle_tree_row_count(8, 2), 4);
+        // A 4-ary tree over 16 leaves: 16 -> 4 -> 1, so 3 rows.
+        assert_eq!(get_merkle_tree_row_count(16, 4), 3);
+        // An 8-ary tree over 64 leaves: 64 -> 8 -> 1, so 3 rows.
+        assert_eq!(get_merkle_tree_row_count(64, 8), 3);
+
+        assert_eq!(get_merkle_tree_row_count(1, 2), 1);
+        assert_eq!(get_merkle_tree_row_count(0, 2), 0);
+    }
+
+    #[test]
+    fn merkle_tree_depth_tracks_arity() {
+        let g = BucketGraph::<PedersenHasher>::new(
+            64,
+            5,
+            0,
+            [0u8; 32],
+            ApiVersion::V1_0,
+            new_seed(),
+        );
+
+        assert_eq!(g.merkle_tree_depth::<U2>(), 7);
+        assert_eq!(g.merkle_tree_depth::<U4>(), 4);
+        assert_eq!(g.merkle_tree_depth::<U8>(), 3);
+    }
 }
@@ -0,0 +1,127 @@
+use std::cmp;
+
+use sha2::{Digest, Sha256};
+
+/// Number of Feistel rounds used when permuting expander-parent indexes.
+/// A handful of rounds is enough to destroy any structure in the input
+/// index while staying cheap to evaluate for every parent of every node.
+const FEISTEL_ROUNDS: usize = 3;
+
+/// Precomputed bit-width parameters for a balanced Feistel permutation over
+/// `[0, num_elements)`. Computing these once per graph avoids recomputing
+/// the bit masks on every call to `permute`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeistelPrecomputed {
+    half_bits: u32,
+    left_mask: u64,
+    right_mask: u64,
+}
+
+/// Precomputes the bit-width parameters needed to permute indexes in
+/// `[0, num_elements)`. The permutation itself operates over a
+/// power-of-two-sized space covering `num_elements`, whose bit width is
+/// rounded up to an even number so it splits evenly into left/right halves
+/// (e.g. 1024 elements for `num_elements == 400`).
+pub fn precompute(num_elements: u64) -> FeistelPrecomputed {
+    let bits = cmp_bits(num_elements);
+    let half_bits = (bits + 1) / 2;
+    let right_mask = (1u64 << half_bits) - 1;
+    let left_mask = right_mask << half_bits;
+
+    FeistelPrecomputed {
+        half_bits,
+        left_mask,
+        right_mask,
+    }
+}
+
+// Smallest number of bits needed to represent every value in
+// `[0, num_elements)`, with a floor of 2 so the permutation always has a
+// non-trivial left/right split.
+fn cmp_bits(num_elements: u64) -> u32 {
+    let bits = 64 - num_elements.saturating_sub(1).leading_zeros();
+    cmp::max(bits, 2)
+}
+
+fn hash_round(key: u64, round: usize, right: u64) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.input(&key.to_le_bytes());
+    hasher.input(&(round as u64).to_le_bytes());
+    hasher.input(&right.to_le_bytes());
+    let digest = hasher.result();
+
+    let mut first_8 = [0u8; 8];
+    first_8.copy_from_slice(&digest[..8]);
+    u64::from_le_bytes(first_8)
+}
+
+fn encode(index: u64, key: u64, precomputed: FeistelPrecomputed) -> u64 {
+    let FeistelPrecomputed {
+        half_bits,
+        left_mask,
+        right_mask,
+    } = precomputed;
+
+    let mut left = (index & left_mask) >> half_bits;
+    let mut right = index & right_mask;
+
+    for round in 0..FEISTEL_ROUNDS {
+        let scrambled = hash_round(key, round, right) & right_mask;
+        let next_right = left ^ scrambled;
+        left = right;
+        right = next_right;
+    }
+
+    (left << half_bits) | right
+}
+
+/// Permutes `index` into `[0, num_elements)` using a keyed, balanced
+/// Feistel cipher. The underlying cipher is a bijection on the padded
+/// power-of-two space, so any image landing outside `[0, num_elements)` is
+/// fed back through the cipher (cycle-walking) until it lands inside it,
+/// which keeps the overall map on `[0, num_elements)` a bijection too.
+pub fn permute(num_elements: u64, index: u64, key: u64, precomputed: FeistelPrecomputed) -> u64 {
+    let mut permuted = index;
+    loop {
+        permuted = encode(permuted, key, precomputed);
+        if permuted < num_elements {
+            return permuted;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permute_is_deterministic() {
+        let precomputed = precompute(400);
+        let a = permute(400, 17, 42, precomputed);
+        let b = permute(400, 17, 42, precomputed);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn permute_stays_in_range() {
+        let num_elements = 400;
+        let precomputed = precompute(num_elements);
+        for index in 0..num_elements {
+            let permuted = permute(num_elements, index, 7, precomputed);
+            assert!(permuted < num_elements);
+        }
+    }
+
+    #[test]
+    fn permute_is_a_bijection() {
+        let num_elements = 400;
+        let precomputed = precompute(num_elements);
+        let mut seen = vec![false; num_elements as usize];
+
+        for index in 0..num_elements {
+            let permuted = permute(num_elements, index, 11, precomputed);
+            assert!(!seen[permuted as usize], "collision at {}", permuted);
+            seen[permuted as usize] = true;
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use generic_array::typenum::{Unsigned, U2, U4, U8};
+use neptune::poseidon::Poseidon;
+use paired::bls12_381::{Bls12, Fr};
+
+use error::*;
+use fr32::{bytes_into_fr, fr_into_bytes};
+use hasher::{Domain, HashFunction, Hasher};
+
+/// Marks a `typenum` unsigned integer as a supported Poseidon tree arity.
+/// Only the arities we ship constants/round-counts for implement it, so a
+/// caller can't accidentally ask for e.g. `U3`.
+pub trait PoseidonArity: Unsigned + Send + Sync + Clone + ::std::fmt::Debug + 'static {}
+
+impl PoseidonArity for U2 {}
+impl PoseidonArity for U4 {}
+impl PoseidonArity for U8 {}
+
+/// A BLS12-381 scalar wrapped up as a Merkle tree domain element, stored as
+/// its little-endian byte encoding.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PoseidonDomain(pub [u8; 32]);
+
+impl AsRef<[u8]> for PoseidonDomain {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Domain for PoseidonDomain {
+    fn into_bytes(&self) -> Vec<u8> {
+        self.as_ref().to_vec()
+    }
+
+    fn try_from_bytes(raw: &[u8]) -> Result<Self> {
+        let fr = bytes_into_fr::<Bls12>(raw)?;
+        Ok(PoseidonDomain::from(fr))
+    }
+
+    fn write_bytes(&self, dest: &mut [u8]) -> Result<()> {
+        dest[..32].copy_from_slice(self.as_ref());
+        Ok(())
+    }
+}
+
+impl From<Fr> for PoseidonDomain {
+    fn from(val: Fr) -> Self {
+        let mut repr = [0u8; 32];
+        repr.copy_from_slice(&fr_into_bytes::<Bls12>(&val));
+        PoseidonDomain(repr)
+    }
+}
+
+impl From<PoseidonDomain> for Fr {
+    fn from(val: PoseidonDomain) -> Self {
+        bytes_into_fr::<Bls12>(&val.0).expect("malformed poseidon domain element")
+    }
+}
+
+/// The Poseidon hash function, evaluated over BLS12-381 scalars. Unlike the
+/// Pedersen hasher, a single Poseidon evaluation can absorb `arity` children
+/// at once, which is what lets `merkle_tree` build shallower, higher-arity
+/// trees: `hash_arity::<U4>` replaces 3 binary Pedersen hashes with one
+/// 4-ary Poseidon hash per internal node.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PoseidonFunction(PoseidonDomain);
+
+impl PoseidonFunction {
+    /// Hashes `arity` children into one parent, as used for internal nodes
+    /// of an arity-`A` Merkle tree.
+    pub fn hash_arity<A: PoseidonArity>(children: &[PoseidonDomain]) -> PoseidonDomain {
+        assert_eq!(children.len(), A::to_usize(), "wrong number of children");
+
+        let preimage: Vec<Fr> = children.iter().map(|c| Fr::from(*c)).collect();
+        let digest = Poseidon::new(&preimage).hash();
+
+        PoseidonDomain::from(digest)
+    }
+}
+
+impl HashFunction<PoseidonDomain> for PoseidonFunction {
+    fn hash(data: &[u8]) -> PoseidonDomain {
+        PoseidonFunction::hash_single_node(data)
+    }
+
+    fn hash_single_node(data: &[u8]) -> PoseidonDomain {
+        let preimage: Vec<Fr> = data
+            .chunks(32)
+            .map(|chunk| bytes_into_fr::<Bls12>(chunk).expect("invalid node bytes"))
+            .collect();
+        PoseidonDomain::from(Poseidon::new(&preimage).hash())
+    }
+
+    fn hash_leaf(data: &[u8]) -> PoseidonDomain {
+        Self::hash_single_node(data)
+    }
+}
+
+/// `Hasher` implementation selecting Poseidon as the hash function for
+/// Merkle trees built over BLS12-381 scalars.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PoseidonHasher {}
+
+impl Hasher for PoseidonHasher {
+    type Domain = PoseidonDomain;
+    type Function = PoseidonFunction;
+
+    fn name() -> String {
+        "PoseidonHasher".into()
+    }
+}
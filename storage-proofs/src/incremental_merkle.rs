@@ -0,0 +1,430 @@
+use hasher::{Domain, HashFunction, Hasher};
+
+/// Maximum supported tree depth; 32 levels covers more than four billion
+/// leaves, far beyond any sector's node count.
+pub const MAX_DEPTH: usize = 32;
+
+fn hash_pair<H: Hasher>(left: &H::Domain, right: &H::Domain) -> H::Domain {
+    let mut bytes = left.into_bytes();
+    bytes.extend(right.into_bytes());
+    H::Function::hash_single_node(&bytes)
+}
+
+/// Precomputes the root of an empty (all-default-leaf) subtree at each
+/// level `0..=depth`, used to pad the right-hand edge of a partially
+/// filled tree so `root()` and authentication paths are well defined
+/// before the tree is full.
+fn empty_roots<H: Hasher>(depth: usize) -> Vec<H::Domain> {
+    let mut roots = Vec::with_capacity(depth + 1);
+    roots.push(H::Domain::default());
+    for level in 1..=depth {
+        let prev = roots[level - 1].clone();
+        roots.push(hash_pair::<H>(&prev, &prev));
+    }
+    roots
+}
+
+/// A partially built subtree: the running state of a binary counter over
+/// appended leaves, used both as the tree's own frontier and, cloned, as
+/// the basis for a witness's authentication path.
+#[derive(Clone, Debug)]
+struct CommitmentTree<H: Hasher> {
+    size: usize,
+    left: Option<H::Domain>,
+    right: Option<H::Domain>,
+    // parents[level] holds the completed hash of the level-(level+1)
+    // subtree immediately left of the current position, once it exists.
+    parents: Vec<Option<H::Domain>>,
+}
+
+impl<H: Hasher> CommitmentTree<H> {
+    fn new() -> Self {
+        CommitmentTree {
+            size: 0,
+            left: None,
+            right: None,
+            parents: Vec::new(),
+        }
+    }
+
+    fn is_complete(&self, depth: usize) -> bool {
+        self.size == (1usize << depth)
+    }
+
+    fn append(&mut self, node: H::Domain) {
+        if self.left.is_none() {
+            self.left = Some(node);
+        } else if self.right.is_none() {
+            self.right = Some(node);
+        } else {
+            let mut combined = hash_pair::<H>(
+                self.left.as_ref().expect("left is some"),
+                self.right.as_ref().expect("right is some"),
+            );
+            self.left = Some(node);
+            self.right = None;
+
+            let mut placed = false;
+            for parent in self.parents.iter_mut() {
+                match parent.take() {
+                    Some(p) => combined = hash_pair::<H>(&p, &combined),
+                    None => {
+                        *parent = Some(combined.clone());
+                        placed = true;
+                        break;
+                    }
+                }
+            }
+            if !placed {
+                self.parents.push(Some(combined));
+            }
+        }
+        self.size += 1;
+    }
+
+    fn root(&self, depth: usize, empty_roots: &[H::Domain]) -> H::Domain {
+        let left = self.left.clone().unwrap_or_else(|| empty_roots[0].clone());
+        let right = self.right.clone().unwrap_or_else(|| empty_roots[0].clone());
+        let mut root = hash_pair::<H>(&left, &right);
+
+        for level in 0..depth.saturating_sub(1) {
+            root = match self.parents.get(level).and_then(|p| p.clone()) {
+                Some(sibling) => hash_pair::<H>(&sibling, &root),
+                None => hash_pair::<H>(&root, &empty_roots[level + 1]),
+            };
+        }
+
+        root
+    }
+}
+
+/// An authentication path from a leaf to the root: one sibling hash per
+/// level, ordered from the leaf upward.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerklePath<H: Hasher> {
+    pub siblings: Vec<H::Domain>,
+}
+
+/// The authentication path of a marked leaf, kept up to date as the owning
+/// tree grows. Rather than storing every leaf, it holds one slot per level:
+/// fixed for good where the snapshot at mark time already determined it,
+/// filled in later as the corresponding subtree completes.
+#[derive(Clone, Debug)]
+struct Witness<H: Hasher> {
+    depth: usize,
+    empty_roots: Vec<H::Domain>,
+    // siblings[level] is known once the subtree on the other side of the
+    // marked leaf at that level is complete; `None` until then.
+    siblings: Vec<Option<H::Domain>>,
+    // The subtree currently being built for the lowest not-yet-known level.
+    cursor: Option<CommitmentTree<H>>,
+    cursor_depth: usize,
+}
+
+impl<H: Hasher> Witness<H> {
+    fn new(tree: CommitmentTree<H>, depth: usize, empty_roots: Vec<H::Domain>) -> Self {
+        // `right` holds the marked leaf itself when it fell on the right
+        // slot of its pair, in which case `left` is its already-fixed
+        // sibling; on the left slot, `right` is empty and level 0 is
+        // resolved by whatever leaf is appended next.
+        let marked_on_right = tree.right.is_some();
+        let mut siblings = Vec::with_capacity(depth);
+        siblings.push(if marked_on_right {
+            Some(tree.left.clone().expect("left recorded at mark time"))
+        } else {
+            None
+        });
+        siblings.extend(tree.parents.iter().cloned());
+        while siblings.len() < depth {
+            siblings.push(None);
+        }
+
+        let cursor_depth = siblings.iter().position(Option::is_none).unwrap_or(depth);
+        Witness {
+            depth,
+            empty_roots,
+            siblings,
+            cursor: None,
+            cursor_depth,
+        }
+    }
+
+    fn advance_cursor_depth(&mut self) {
+        self.cursor_depth = self
+            .siblings
+            .iter()
+            .position(Option::is_none)
+            .unwrap_or(self.depth);
+    }
+
+    fn append(&mut self, leaf: H::Domain) {
+        if self.cursor_depth >= self.depth {
+            return;
+        }
+
+        if let Some(cursor) = &mut self.cursor {
+            cursor.append(leaf);
+            if cursor.is_complete(self.cursor_depth) {
+                self.siblings[self.cursor_depth] =
+                    Some(cursor.root(self.cursor_depth, &self.empty_roots));
+                self.cursor = None;
+                self.advance_cursor_depth();
+            }
+            return;
+        }
+
+        if self.cursor_depth == 0 {
+            self.siblings[0] = Some(leaf);
+            self.advance_cursor_depth();
+        } else {
+            let mut cursor = CommitmentTree::new();
+            cursor.append(leaf);
+            self.cursor = Some(cursor);
+        }
+    }
+
+    fn path(&self) -> MerklePath<H> {
+        let siblings = (0..self.depth)
+            .map(|level| match &self.siblings[level] {
+                Some(sibling) => sibling.clone(),
+                None if level == self.cursor_depth => match &self.cursor {
+                    Some(cursor) => cursor.root(self.cursor_depth, &self.empty_roots),
+                    None => self.empty_roots[level].clone(),
+                },
+                None => self.empty_roots[level].clone(),
+            })
+            .collect();
+
+        MerklePath { siblings }
+    }
+}
+
+/// An append-only Merkle tree that only keeps the frontier (the right-most
+/// filled node at each level) and whatever witnesses were explicitly
+/// marked resident, so `append` never requires rebuilding the tree from
+/// the full set of leaves.
+#[derive(Clone, Debug)]
+pub struct IncrementalMerkleTree<H: Hasher> {
+    depth: usize,
+    tree: CommitmentTree<H>,
+    empty_roots: Vec<H::Domain>,
+    witnesses: Vec<(usize, Witness<H>)>,
+    checkpoints: Vec<(CommitmentTree<H>, Vec<(usize, Witness<H>)>)>,
+}
+
+impl<H: Hasher> IncrementalMerkleTree<H> {
+    pub fn new(depth: usize) -> Self {
+        assert!(depth > 0 && depth <= MAX_DEPTH);
+        IncrementalMerkleTree {
+            depth,
+            tree: CommitmentTree::new(),
+            empty_roots: empty_roots::<H>(depth),
+            witnesses: Vec::new(),
+            checkpoints: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.size == 0
+    }
+
+    pub fn root(&self) -> H::Domain {
+        self.tree.root(self.depth, &self.empty_roots)
+    }
+
+    /// Appends `leaf`, updating the frontier and every currently marked
+    /// witness in `O(log n)`.
+    pub fn append(&mut self, leaf: H::Domain) {
+        assert!(self.tree.size < (1usize << self.depth), "tree is full");
+
+        for (_, witness) in self.witnesses.iter_mut() {
+            witness.append(leaf.clone());
+        }
+
+        self.tree.append(leaf);
+    }
+
+    /// Marks the most recently appended leaf so its authentication path is
+    /// kept up to date as more leaves are appended. Returns the marked
+    /// leaf's position.
+    pub fn mark(&mut self) -> usize {
+        assert!(self.tree.size > 0, "cannot mark an empty tree");
+        let position = self.tree.size - 1;
+
+        self.witnesses.push((
+            position,
+            Witness::new(self.tree.clone(), self.depth, self.empty_roots.clone()),
+        ));
+
+        position
+    }
+
+    /// Returns the authentication path for `position`, if it was marked.
+    pub fn witness(&self, position: usize) -> Option<MerklePath<H>> {
+        self.witnesses
+            .iter()
+            .find(|(p, _)| *p == position)
+            .map(|(_, w)| w.path())
+    }
+
+    /// Saves the current tree and witness state so a later `rewind` can
+    /// return to it.
+    pub fn checkpoint(&mut self) {
+        self.checkpoints
+            .push((self.tree.clone(), self.witnesses.clone()));
+    }
+
+    /// Restores the state saved by the most recent `checkpoint`, discarding
+    /// any leaves (and witness updates) appended since. Returns `false` if
+    /// there was no checkpoint to rewind to.
+    pub fn rewind(&mut self) -> bool {
+        match self.checkpoints.pop() {
+            Some((tree, witnesses)) => {
+                self.tree = tree;
+                self.witnesses = witnesses;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Verifies that `path` is a valid authentication path for `leaf` at
+/// `position` against `root`.
+pub fn check_inclusion<H: Hasher>(
+    path: &MerklePath<H>,
+    position: usize,
+    leaf: &H::Domain,
+    root: &H::Domain,
+) -> bool {
+    let mut current = leaf.clone();
+    let mut index = position;
+
+    for sibling in &path.siblings {
+        current = if index & 1 == 0 {
+            hash_pair::<H>(&current, sibling)
+        } else {
+            hash_pair::<H>(sibling, &current)
+        };
+        index >>= 1;
+    }
+
+    current == *root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use fr32::fr_into_bytes;
+    use hasher::PedersenHasher;
+    use paired::bls12_381::{Bls12, Fr};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    fn leaf(rng: &mut XorShiftRng) -> <PedersenHasher as Hasher>::Domain {
+        let fr: Fr = rng.gen();
+        <PedersenHasher as Hasher>::Domain::try_from_bytes(&fr_into_bytes::<Bls12>(&fr)).unwrap()
+    }
+
+    #[test]
+    fn root_matches_full_rebuild() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let depth = 4;
+        let leaves: Vec<_> = (0..1 << depth).map(|_| leaf(&mut rng)).collect();
+
+        let mut tree = IncrementalMerkleTree::<PedersenHasher>::new(depth);
+        for l in &leaves {
+            tree.append(l.clone());
+        }
+
+        // Rebuild the same full binary tree directly from the leaves and
+        // confirm the incremental root agrees.
+        let mut level = leaves.clone();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| hash_pair::<PedersenHasher>(&pair[0], &pair[1]))
+                .collect();
+        }
+
+        assert_eq!(tree.root(), level[0]);
+    }
+
+    #[test]
+    fn witness_verifies_after_further_appends() {
+        let mut rng = XorShiftRng::from_seed([5, 6, 7, 8]);
+        let depth = 4;
+
+        let mut tree = IncrementalMerkleTree::<PedersenHasher>::new(depth);
+
+        let marked_leaf = leaf(&mut rng);
+        tree.append(marked_leaf.clone());
+        let position = tree.mark();
+
+        for _ in 0..(1 << depth) - 1 {
+            tree.append(leaf(&mut rng));
+        }
+
+        let path = tree.witness(position).expect("witness exists");
+        assert!(check_inclusion::<PedersenHasher>(
+            &path,
+            position,
+            &marked_leaf,
+            &tree.root(),
+        ));
+    }
+
+    #[test]
+    fn witness_verifies_when_marking_mid_tree() {
+        let mut rng = XorShiftRng::from_seed([13, 14, 15, 16]);
+        let depth = 4;
+
+        let mut tree = IncrementalMerkleTree::<PedersenHasher>::new(depth);
+
+        let mut marked_leaf = None;
+        let mut position = None;
+        for i in 0..(1 << depth) {
+            let l = leaf(&mut rng);
+            tree.append(l.clone());
+            // Mark an odd (right-slot) position with more than one level
+            // still unresolved at mark time, so the witness has to buffer
+            // several completed subtree roots before the path is read.
+            if i == 5 {
+                marked_leaf = Some(l);
+                position = Some(tree.mark());
+            }
+        }
+        let marked_leaf = marked_leaf.expect("marked");
+        let position = position.expect("marked");
+
+        let path = tree.witness(position).expect("witness exists");
+        assert!(check_inclusion::<PedersenHasher>(
+            &path,
+            position,
+            &marked_leaf,
+            &tree.root(),
+        ));
+    }
+
+    #[test]
+    fn rewind_discards_appends_since_checkpoint() {
+        let mut rng = XorShiftRng::from_seed([9, 10, 11, 12]);
+        let depth = 3;
+
+        let mut tree = IncrementalMerkleTree::<PedersenHasher>::new(depth);
+        tree.append(leaf(&mut rng));
+        tree.checkpoint();
+        let root_at_checkpoint = tree.root();
+
+        tree.append(leaf(&mut rng));
+        assert_ne!(tree.root(), root_at_checkpoint);
+
+        assert!(tree.rewind());
+        assert_eq!(tree.root(), root_at_checkpoint);
+        assert_eq!(tree.len(), 1);
+    }
+}
@@ -0,0 +1,451 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Result as IoResult, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use hasher::{Domain, HashFunction, Hasher};
+
+/// Byte width of one stored Merkle tree node; every hasher in this crate
+/// encodes its domain as a 32-byte value.
+const ELEMENT_SIZE: usize = 32;
+
+/// Hashes `children` (in left-to-right order) into their parent. Generic
+/// over arity: concatenating every child's bytes and running them through
+/// the hasher's single-node function works for any arity.
+fn hash_children<H: Hasher>(children: &[H::Domain]) -> H::Domain {
+    let mut bytes = Vec::with_capacity(children.len() * ELEMENT_SIZE);
+    for child in children {
+        bytes.extend(child.into_bytes());
+    }
+    H::Function::hash_single_node(&bytes)
+}
+
+/// Returns the length of each row of an arity-`arity` tree over `leaves`
+/// leaves, from the leaf row up to (and including) the single-node root
+/// row.
+fn row_lengths(leaves: usize, arity: usize) -> Vec<usize> {
+    let mut lens = vec![leaves];
+    while *lens.last().expect("at least one row") > 1 {
+        let prev = *lens.last().expect("at least one row");
+        assert_eq!(prev % arity, 0, "row length not divisible by arity");
+        lens.push(prev / arity);
+    }
+    lens
+}
+
+/// Names and locates the on-disk store backing a disk-backed or
+/// level-cache Merkle tree, and how many of its bottom rows are discarded
+/// (rebuilt on demand from the replica) rather than kept resident.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoreConfig {
+    /// Directory the store's data file(s) live in.
+    pub path: PathBuf,
+    /// Identifies this store's data file within `path`.
+    pub id: String,
+    /// Number of bottom rows not persisted in the store; they are
+    /// regenerated from the replica when a proof needs them.
+    pub rows_to_discard: usize,
+}
+
+impl StoreConfig {
+    pub fn new<P: AsRef<Path>, S: Into<String>>(path: P, id: S, rows_to_discard: usize) -> Self {
+        StoreConfig {
+            path: path.as_ref().to_path_buf(),
+            id: id.into(),
+            rows_to_discard,
+        }
+    }
+
+    /// Returns a new config for the same store directory and discard count,
+    /// but a different `id` -- used when deriving a config for a tree built
+    /// on top of (or alongside) this one.
+    pub fn from_config<S: Into<String>>(config: &StoreConfig, id: S) -> Self {
+        StoreConfig {
+            path: config.path.clone(),
+            id: id.into(),
+            rows_to_discard: config.rows_to_discard,
+        }
+    }
+
+    pub fn data_path(&self) -> PathBuf {
+        self.path.join(format!("sc-{}-data.dat", self.id))
+    }
+}
+
+/// Points a level-cache Merkle tree at the on-disk replica holding the rows
+/// that were discarded from its `StoreConfig`, so they can be read back (or
+/// regenerated) on demand while proving.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplicaConfig {
+    /// Path to the replica file containing the full, un-discarded data.
+    pub path: PathBuf,
+    /// Byte offset of this tree's replica within `path`, for the common
+    /// case of multiple trees packed into one replica file.
+    pub offset: usize,
+}
+
+impl ReplicaConfig {
+    pub fn new<P: AsRef<Path>>(path: P, offset: usize) -> Self {
+        ReplicaConfig {
+            path: path.as_ref().to_path_buf(),
+            offset,
+        }
+    }
+}
+
+/// The persisted rows of a disk-backed or level-cache Merkle tree, held in
+/// a single flat file at `config.data_path()`.
+#[derive(Debug)]
+pub struct DiskStore {
+    file: File,
+    len: usize,
+}
+
+impl DiskStore {
+    /// Creates (or truncates) `config`'s backing file, sized to hold `len`
+    /// nodes.
+    pub fn create(config: &StoreConfig, len: usize) -> IoResult<Self> {
+        if let Some(parent) = config.data_path().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(config.data_path())?;
+        file.set_len((len * ELEMENT_SIZE) as u64)?;
+        Ok(DiskStore { file, len })
+    }
+
+    /// Opens a store previously written by `create`.
+    pub fn open(config: &StoreConfig, len: usize) -> IoResult<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(config.data_path())?;
+        Ok(DiskStore { file, len })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn write_at(&mut self, index: usize, node: &[u8]) -> IoResult<()> {
+        self.file
+            .seek(SeekFrom::Start((index * ELEMENT_SIZE) as u64))?;
+        self.file.write_all(node)
+    }
+
+    fn read_at<H: Hasher>(&mut self, index: usize) -> IoResult<H::Domain> {
+        let mut buf = vec![0u8; ELEMENT_SIZE];
+        self.file
+            .seek(SeekFrom::Start((index * ELEMENT_SIZE) as u64))?;
+        self.file.read_exact(&mut buf)?;
+        Ok(H::Domain::try_from_bytes(&buf).expect("store holds valid domain elements"))
+    }
+}
+
+/// An inclusion proof for a `DiskMerkleTree`/`LevelCacheMerkleTree` leaf.
+/// `levels[i]` holds level `i`'s sibling group (every child of that node's
+/// parent except the node itself, left-to-right) together with the node's
+/// index within that group, so `validate` can re-insert it and re-hash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiskProof<H: Hasher> {
+    leaf: H::Domain,
+    root: H::Domain,
+    levels: Vec<(Vec<H::Domain>, usize)>,
+}
+
+impl<H: Hasher> DiskProof<H> {
+    pub fn root(&self) -> H::Domain {
+        self.root.clone()
+    }
+
+    /// Recomputes the root from `leaf` and `levels` and checks it against
+    /// the root this proof was generated against.
+    pub fn validate(&self) -> bool {
+        let mut current = self.leaf.clone();
+        for (siblings, index) in &self.levels {
+            let mut children = siblings.clone();
+            children.insert(*index, current.clone());
+            current = hash_children::<H>(&children);
+        }
+        current == self.root
+    }
+}
+
+/// A disk-backed Merkle tree: every row from `rows_to_discard` up to the
+/// root lives in a `DiskStore` rather than in memory. Built (and its store
+/// populated) by `build_disk_backed_tree`; re-opened for proving, once a
+/// replica of its leaves exists, via `open_level_cache_tree`.
+pub struct DiskMerkleTree<H: Hasher> {
+    root: H::Domain,
+    _store: DiskStore,
+}
+
+impl<H: Hasher> DiskMerkleTree<H> {
+    pub fn root(&self) -> H::Domain {
+        self.root.clone()
+    }
+}
+
+/// Builds an arity-`arity` Merkle tree over `leaves`, writing every row from
+/// `rows_to_discard` up to the root into `config`'s `DiskStore` and
+/// dropping the bottom `rows_to_discard` rows rather than persisting them.
+/// Returns the tree's `DiskStore` handle and root. The dropped rows can
+/// later be recovered from a replica of `leaves` via `gen_proof_with_replica`
+/// (or, through the `Graph` trait, `lc_merkle_tree`/`LevelCacheMerkleTree`).
+pub fn build_disk_backed_tree<H: Hasher>(
+    leaves: &[H::Domain],
+    config: &StoreConfig,
+    rows_to_discard: usize,
+    arity: usize,
+) -> IoResult<DiskMerkleTree<H>> {
+    let mut rows = vec![leaves.to_vec()];
+    while rows.last().expect("at least one row").len() > 1 {
+        let next = rows
+            .last()
+            .expect("at least one row")
+            .chunks(arity)
+            .map(hash_children::<H>)
+            .collect();
+        rows.push(next);
+    }
+    let root = rows.last().expect("at least one row")[0].clone();
+
+    assert!(rows_to_discard < rows.len(), "cannot discard the root row");
+    let persisted_rows = &rows[rows_to_discard..];
+    let total_nodes: usize = persisted_rows.iter().map(Vec::len).sum();
+
+    let mut store = DiskStore::create(config, total_nodes)?;
+    let mut index = 0;
+    for row in persisted_rows {
+        for node in row {
+            store.write_at(index, &node.into_bytes())?;
+            index += 1;
+        }
+    }
+
+    Ok(DiskMerkleTree {
+        root,
+        _store: store,
+    })
+}
+
+/// Recomputes the rows discarded by `build_disk_backed_tree` from the raw
+/// leaves held in `replica_config`'s replica file.
+fn regenerate_discarded_rows<H: Hasher>(
+    replica_config: &ReplicaConfig,
+    total_leaves: usize,
+    rows_to_discard: usize,
+    arity: usize,
+) -> IoResult<Vec<Vec<H::Domain>>> {
+    let mut file = File::open(&replica_config.path)?;
+    let mut leaves = Vec::with_capacity(total_leaves);
+    for i in 0..total_leaves {
+        let mut buf = vec![0u8; ELEMENT_SIZE];
+        file.seek(SeekFrom::Start(
+            (replica_config.offset + i * ELEMENT_SIZE) as u64,
+        ))?;
+        file.read_exact(&mut buf)?;
+        leaves.push(H::Domain::try_from_bytes(&buf).expect("replica holds valid leaves"));
+    }
+
+    let mut rows = vec![leaves];
+    for _ in 1..rows_to_discard {
+        let next = rows
+            .last()
+            .expect("at least one row")
+            .chunks(arity)
+            .map(hash_children::<H>)
+            .collect();
+        rows.push(next);
+    }
+    Ok(rows)
+}
+
+/// Returns the authentication path for `leaf_index`, reading the rows kept
+/// resident in `store` and regenerating the rows discarded at build time
+/// from `replica_config`'s replica.
+pub fn gen_proof_with_replica<H: Hasher>(
+    store: &mut DiskStore,
+    replica_config: &ReplicaConfig,
+    total_leaves: usize,
+    rows_to_discard: usize,
+    arity: usize,
+    leaf_index: usize,
+) -> IoResult<DiskProof<H>> {
+    assert!(leaf_index < total_leaves, "leaf index out of range");
+
+    let lens = row_lengths(total_leaves, arity);
+    let depth = lens.len() - 1;
+    let discarded = regenerate_discarded_rows::<H>(replica_config, total_leaves, rows_to_discard, arity)?;
+
+    let leaf = discarded[0][leaf_index].clone();
+
+    let mut levels = Vec::with_capacity(depth);
+    let mut index = leaf_index;
+    let mut persisted_offset = 0usize;
+
+    for level in 0..depth {
+        let group_start = (index / arity) * arity;
+        let index_in_group = index % arity;
+
+        let mut siblings = Vec::with_capacity(arity - 1);
+        if level < rows_to_discard {
+            for k in 0..arity {
+                if k != index_in_group {
+                    siblings.push(discarded[level][group_start + k].clone());
+                }
+            }
+        } else {
+            for k in 0..arity {
+                if k != index_in_group {
+                    siblings.push(store.read_at::<H>(persisted_offset + group_start + k)?);
+                }
+            }
+            persisted_offset += lens[level];
+        }
+
+        levels.push((siblings, index_in_group));
+        index /= arity;
+    }
+
+    // `build_disk_backed_tree` only ever discards rows below
+    // `rows_to_discard <= depth`, so the root row (at `depth`) is always
+    // persisted -- it's always the last node written to the store.
+    let root = store.read_at::<H>(store.len() - 1)?;
+
+    Ok(DiskProof {
+        leaf,
+        root,
+        levels,
+    })
+}
+
+/// A level-cache Merkle tree: only the top rows are kept resident in a
+/// `DiskStore` (opened from a previously built `StoreConfig`); the bottom
+/// `rows_to_discard` rows are regenerated on demand from `replica_config`
+/// while generating a proof. Returned by `Graph::lc_merkle_tree`.
+pub struct LevelCacheMerkleTree<H: Hasher> {
+    store: DiskStore,
+    replica_config: ReplicaConfig,
+    total_leaves: usize,
+    rows_to_discard: usize,
+    arity: usize,
+    _h: PhantomData<H>,
+}
+
+impl<H: Hasher> LevelCacheMerkleTree<H> {
+    pub fn gen_proof(&mut self, leaf_index: usize) -> IoResult<DiskProof<H>> {
+        gen_proof_with_replica::<H>(
+            &mut self.store,
+            &self.replica_config,
+            self.total_leaves,
+            self.rows_to_discard,
+            self.arity,
+            leaf_index,
+        )
+    }
+}
+
+/// Opens the level-cache tree previously built (and persisted) by
+/// `build_disk_backed_tree` at `config`, pairing it with the replica that
+/// holds the rows `config.rows_to_discard` left unpersisted.
+pub fn open_level_cache_tree<H: Hasher>(
+    config: &StoreConfig,
+    replica_config: ReplicaConfig,
+    total_leaves: usize,
+    arity: usize,
+) -> IoResult<LevelCacheMerkleTree<H>> {
+    let lens = row_lengths(total_leaves, arity);
+    let total_nodes: usize = lens[config.rows_to_discard..].iter().sum();
+    let store = DiskStore::open(config, total_nodes)?;
+
+    Ok(LevelCacheMerkleTree {
+        store,
+        replica_config,
+        total_leaves,
+        rows_to_discard: config.rows_to_discard,
+        arity,
+        _h: PhantomData,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hasher::poseidon::PoseidonHasher;
+
+    #[test]
+    fn store_config_data_path() {
+        let config = StoreConfig::new("/tmp/sectors", "tree-r-last", 2);
+        assert_eq!(
+            config.data_path(),
+            PathBuf::from("/tmp/sectors/sc-tree-r-last-data.dat")
+        );
+    }
+
+    #[test]
+    fn store_config_from_config_keeps_discard_count() {
+        let base = StoreConfig::new("/tmp/sectors", "tree-r-last", 2);
+        let derived = StoreConfig::from_config(&base, "tree-c");
+
+        assert_eq!(derived.path, base.path);
+        assert_eq!(derived.rows_to_discard, base.rows_to_discard);
+        assert_eq!(derived.id, "tree-c");
+    }
+
+    #[test]
+    fn disk_backed_tree_regenerates_proof_from_replica() {
+        let dir = std::env::temp_dir().join(format!("store-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let leaves: Vec<<PoseidonHasher as Hasher>::Domain> = (0u64..8)
+            .map(|i| {
+                let mut bytes = [0u8; ELEMENT_SIZE];
+                bytes[..8].copy_from_slice(&i.to_le_bytes());
+                <PoseidonHasher as Hasher>::Domain::try_from_bytes(&bytes).unwrap()
+            })
+            .collect();
+
+        let replica_path = dir.join("replica.dat");
+        {
+            let mut replica = File::create(&replica_path).unwrap();
+            for leaf in &leaves {
+                replica.write_all(&leaf.into_bytes()).unwrap();
+            }
+        }
+        let replica_config = ReplicaConfig::new(&replica_path, 0);
+
+        let config = StoreConfig::new(&dir, "tree-test", 1);
+        let tree =
+            build_disk_backed_tree::<PoseidonHasher>(&leaves, &config, config.rows_to_discard, 2)
+                .unwrap();
+        let root = tree.root();
+
+        // Rebuild directly from the leaves to get an independent root.
+        let mut level = leaves.clone();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(hash_children::<PoseidonHasher>)
+                .collect();
+        }
+        assert_eq!(root, level[0]);
+
+        let mut lc_tree =
+            open_level_cache_tree::<PoseidonHasher>(&config, replica_config, leaves.len(), 2)
+                .unwrap();
+        for leaf_index in 0..leaves.len() {
+            let proof = lc_tree.gen_proof(leaf_index).unwrap();
+            assert_eq!(proof.root(), root);
+            assert!(proof.validate());
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}